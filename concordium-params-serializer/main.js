@@ -1,44 +1,226 @@
 const { Buffer } = require("buffer/");
+const { randomUUID } = require("crypto");
 const bodyParser = require("body-parser");
 const express = require("express");
 const logger = require("morgan");
-const { serializeInitContractParameters, serializeUpdateContractParameters } = require("@concordium/common-sdk");
+const {
+  SchemaVersion,
+  deserializeContractState,
+  deserializeReceiveError,
+  deserializeTypeValue,
+  getInitContractParameterSchema,
+  getUpdateContractParameterSchema,
+  serializeInitContractParameters,
+  serializeUpdateContractParameters,
+} = require("@concordium/common-sdk");
 
 const hostname = "0.0.0.0";
 const port = 7433;
 
+// Schema versions that may be passed explicitly for schemas without an embedded version.
+const supportedSchemaVersions = Object.values(SchemaVersion).filter(Number.isInteger);
+
+class RequestError extends Error {
+  constructor(status, message) {
+    super(message);
+    this.status = status;
+  }
+}
+
 const app = express();
+app.use((req, res, next) => {
+  // Reuse the caller's ID if provided such that requests may be followed across services.
+  // It's echoed into logs and responses, so anything unusual is replaced.
+  const requestId = req.get("X-Request-Id");
+  req.id = requestId && /^[\w.-]{1,128}$/.test(requestId) ? requestId : randomUUID();
+  res.setHeader("X-Request-Id", req.id);
+  next();
+});
+logger.token("id", (req) => req.id);
+// Registered before the body parser such that requests with malformed bodies are logged as well.
+app.use(logger(":id :method :url :status :response-time ms - :res[content-length]"));
 app.use(bodyParser.json()); // requires header 'Content-Type: application/json' to be set
-app.use(logger("dev"));
+const textBodyParser = bodyParser.text(); // requires header 'Content-Type: text/plain' to be set
 
-function parseQueryParams(req, usesReceiveFunctionName) {
-  const { schema, contract_name, receive_function_name, schema_version } = req.query;
+function stringQueryParam(req, name) {
+  // Express parses repeated or bracketed parameters into arrays and objects.
+  const value = req.query[name];
+  if (value !== undefined && typeof value !== "string") {
+    throw new RequestError(400, `parameter '${name}' must be provided exactly once`);
+  }
+  return value;
+}
+
+function parseSchemaVersion(schemaVersion) {
+  if (!schemaVersion) {
+    return undefined;
+  }
+  const version = Number(schemaVersion);
+  if (!/^[0-9]+$/.test(schemaVersion) || !supportedSchemaVersions.includes(version)) {
+    throw new RequestError(
+      400,
+      `parameter 'schema_version' must be one of ${supportedSchemaVersions.join(", ")}`,
+    );
+  }
+  return version;
+}
+
+function parseQueryParams(req, usesReceiveFunctionName, usesSchemaVersion = true) {
+  const schema = stringQueryParam(req, "schema");
+  const contract_name = stringQueryParam(req, "contract_name");
+  const receive_function_name = stringQueryParam(req, "receive_function_name");
+  const schema_version = stringQueryParam(req, "schema_version");
   if (!schema) {
-    throw new Error("missing parameter 'schema'");
+    throw new RequestError(400, "missing parameter 'schema'");
   }
   const schemaBuf = Buffer.from(schema, "base64");
   if (schemaBuf.toString("base64") !== schema) {
-    throw new Error("parameter 'schema' is not valid base64 - did you remember to URL encode it?");
+    throw new RequestError(400, "parameter 'schema' is not valid base64 - did you remember to URL encode it?");
+  }
+  if (!contract_name) {
+    throw new RequestError(400, "missing parameter 'contract_name'");
   }
   if (usesReceiveFunctionName && !receive_function_name) {
-    throw new Error("missing parameter 'receive_function_name'");
+    throw new RequestError(400, "missing parameter 'receive_function_name'");
   }
   if (!usesReceiveFunctionName && receive_function_name) {
-    throw new Error("unexpected parameter 'receive_function_name'");
+    throw new RequestError(400, "unexpected parameter 'receive_function_name'");
+  }
+  if (!usesSchemaVersion && schema_version) {
+    throw new RequestError(400, "unexpected parameter 'schema_version'");
   }
   return {
     schema: schemaBuf,
     contractName: contract_name,
     receiveFunctionName: receive_function_name,
-    schemaVersion: schema_version ? Number.parseInt(schema_version) : undefined,
+    schemaVersion: parseSchemaVersion(schema_version),
     verboseErrorMessage: true,
   };
 }
 
+// Returns the message of an error thrown by the SDK for rejecting its input,
+// or undefined if the error is an internal one caused by invalid arguments or a bug.
+function sdkRejectionMessage(e) {
+  if (typeof e === "string") {
+    return e;
+  }
+  if (e instanceof Error && !(e instanceof TypeError) && !(e instanceof RangeError)) {
+    return e.message;
+  }
+  return undefined;
+}
+
+function callSdk(fn) {
+  try {
+    return fn();
+  } catch (e) {
+    const message = sdkRejectionMessage(e);
+    if (message === undefined) {
+      throw e;
+    }
+    throw new RequestError(422, message);
+  }
+}
+
+function parseHexBody(req) {
+  if (typeof req.body !== "string") {
+    throw new RequestError(400, "missing hex encoded body - did you remember to set 'Content-Type: text/plain'?");
+  }
+  const hex = req.body.trim();
+  if (!/^([0-9a-fA-F]{2})*$/.test(hex)) {
+    throw new RequestError(400, "body is not valid hex");
+  }
+  return Buffer.from(hex, "hex");
+}
+
+function applyJsonPointer(value, pointer) {
+  if (!pointer) {
+    return value;
+  }
+  if (!pointer.startsWith("/")) {
+    throw new RequestError(400, "parameter 'query' must be a JSON pointer (e.g. '/to/Account/0')");
+  }
+  for (const token of pointer.slice(1).split("/")) {
+    const key = token.replace(/~1/g, "/").replace(/~0/g, "~");
+    const isIndex = /^(0|[1-9][0-9]*)$/.test(key);
+    if (
+      value === null ||
+      typeof value !== "object" ||
+      (Array.isArray(value) && !isIndex) ||
+      !Object.prototype.hasOwnProperty.call(value, key)
+    ) {
+      throw new RequestError(422, `query '${pointer}' doesn't match the decoded value`);
+    }
+    value = value[key];
+  }
+  return value;
+}
+
+function parseMaxListItems(maxListItems) {
+  if (!maxListItems) {
+    return undefined;
+  }
+  if (!/^[0-9]+$/.test(maxListItems)) {
+    throw new RequestError(400, "parameter 'max_list_items' must be a non-negative integer");
+  }
+  return Number(maxListItems);
+}
+
+function truncateLists(value, maxListItems) {
+  if (Array.isArray(value)) {
+    const items = value.slice(0, maxListItems).map((item) => truncateLists(item, maxListItems));
+    if (value.length > maxListItems) {
+      items.push({ truncated: true, total: value.length });
+    }
+    return items;
+  }
+  if (value !== null && typeof value === "object") {
+    return Object.fromEntries(Object.entries(value).map(([key, item]) => [key, truncateLists(item, maxListItems)]));
+  }
+  return value;
+}
+
+function sendDecoded(req, res, value) {
+  value = applyJsonPointer(value, stringQueryParam(req, "query"));
+  const maxListItems = parseMaxListItems(stringQueryParam(req, "max_list_items"));
+  if (maxListItems !== undefined) {
+    value = truncateLists(value, maxListItems);
+  }
+  res.setHeader("Content-Type", "application/json");
+  res.write(JSON.stringify(value));
+  res.end("\n");
+}
+
+function resolveInitContractName(contractName, schema, schemaVersion) {
+  // Accept the name of the init function in place of the contract name.
+  // The name as given takes precedence such that contracts whose own name starts with 'init_' resolve correctly.
+  if (!contractName.startsWith("init_")) {
+    return contractName;
+  }
+  const candidates = [contractName, contractName.slice("init_".length)];
+  const messages = [];
+  for (const name of candidates) {
+    try {
+      getInitContractParameterSchema(schema, name, schemaVersion);
+      return name;
+    } catch (e) {
+      const message = sdkRejectionMessage(e);
+      if (message === undefined) {
+        throw e;
+      }
+      messages.push(`'${name}' (${message})`);
+    }
+  }
+  throw new RequestError(422, `no init parameter found in the schema for contract ${messages.join(" or ")}`);
+}
+
 app.post("/init", (req, res) => {
   const parameters = req.body;
-  const { schema, contractName, schemaVersion, verboseErrorMessage } = parseQueryParams(req, false);
-  const buf = serializeInitContractParameters(contractName, parameters, schema, schemaVersion, verboseErrorMessage);
+  const { schema, contractName: name, schemaVersion, verboseErrorMessage } = parseQueryParams(req, false);
+  const contractName = resolveInitContractName(name, schema, schemaVersion);
+  const buf = callSdk(() =>
+    serializeInitContractParameters(contractName, parameters, schema, schemaVersion, verboseErrorMessage),
+  );
   res.setHeader("Content-Type", "text/plain");
   res.write(buf.toString("hex"));
   res.end("\n");
@@ -47,12 +229,82 @@ app.post("/init", (req, res) => {
 app.post("/update", (req, res) => {
   const parameters = req.body;
   const { schema, contractName, receiveFunctionName, schemaVersion, verboseErrorMessage } = parseQueryParams(req, true);
-  const buf = serializeUpdateContractParameters(contractName, receiveFunctionName, parameters, schema, schemaVersion, verboseErrorMessage);
+  const buf = callSdk(() =>
+    serializeUpdateContractParameters(contractName, receiveFunctionName, parameters, schema, schemaVersion, verboseErrorMessage),
+  );
   res.setHeader("Content-Type", "text/plain");
   res.write(buf.toString("hex"));
   res.end("\n");
 });
 
+app.post("/init/decode", textBodyParser, (req, res) => {
+  const { schema, contractName: name, schemaVersion, verboseErrorMessage } = parseQueryParams(req, false);
+  const parameter = parseHexBody(req);
+  const contractName = resolveInitContractName(name, schema, schemaVersion);
+  const value = callSdk(() => {
+    const parameterSchema = getInitContractParameterSchema(schema, contractName, schemaVersion);
+    return deserializeTypeValue(parameter, parameterSchema, verboseErrorMessage);
+  });
+  sendDecoded(req, res, value);
+});
+
+app.post("/update/decode", textBodyParser, (req, res) => {
+  const { schema, contractName, receiveFunctionName, schemaVersion, verboseErrorMessage } = parseQueryParams(req, true);
+  const parameter = parseHexBody(req);
+  const value = callSdk(() => {
+    const parameterSchema = getUpdateContractParameterSchema(schema, contractName, receiveFunctionName, schemaVersion);
+    return deserializeTypeValue(parameter, parameterSchema, verboseErrorMessage);
+  });
+  sendDecoded(req, res, value);
+});
+
+app.post("/update/error", textBodyParser, (req, res) => {
+  // The SDK doesn't take a schema version for decoding errors, so it must be embedded into the schema.
+  const { schema, contractName, receiveFunctionName, verboseErrorMessage } = parseQueryParams(req, true, false);
+  const error = parseHexBody(req);
+  const value = callSdk(() =>
+    deserializeReceiveError(error, schema, contractName, receiveFunctionName, verboseErrorMessage),
+  );
+  sendDecoded(req, res, value);
+});
+
+app.post("/state", textBodyParser, (req, res) => {
+  // Only V0 contracts have their state described by the schema.
+  const { schema, contractName, verboseErrorMessage } = parseQueryParams(req, false, false);
+  const state = parseHexBody(req);
+  const value = callSdk(() => deserializeContractState(contractName, schema, state, verboseErrorMessage));
+  sendDecoded(req, res, value);
+});
+
+// The service holds no state and has no dependencies to wait for,
+// so it's ready as soon as it's alive.
+for (const path of ["/health", "/live", "/ready"]) {
+  app.get(path, (req, res) => {
+    res.json({ status: "ok" });
+  });
+}
+
+app.get("/version", (req, res) => {
+  res.json({ schema_versions: supportedSchemaVersions });
+});
+
+app.use((req, res) => {
+  res.status(404).json({ error: `no endpoint '${req.method} ${req.path}'`, request_id: req.id });
+});
+
+app.use((err, req, res, next) => {
+  if (res.headersSent) {
+    return next(err);
+  }
+  // Errors raised by body-parser (e.g. malformed JSON) carry their own status and are marked as safe to expose.
+  if (err instanceof RequestError || err.expose) {
+    res.status(err.status).json({ error: err.message, request_id: req.id });
+  } else {
+    console.error(`Request ${req.id} failed:`, err);
+    res.status(500).json({ error: "internal error", request_id: req.id });
+  }
+});
+
 app.listen(port, hostname, () => {
   console.log(`Listening on port ${port}.`);
 });